@@ -0,0 +1,215 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! An alternative statement dissemination path built directly on top of `sc-network-gossip`'s
+//! [`GossipEngine`] / [`Validator`] abstraction.
+//!
+//! [`StatementHandler`](crate::StatementHandler) hand-rolls peer bookkeeping, known-item LRU
+//! tracking, role filtering and stream-opened/closed handling on top of the raw notification
+//! service. [`StatementsValidator`] instead plugs into `GossipEngine`, which already provides all
+//! of that plus message expiry and per-topic routing, so the only statement-specific logic left is
+//! the [`Validator::validate`] hook: submit the incoming statement to the
+//! [`StatementStore`] and translate the [`SubmitResult`] into a gossip keep/discard decision and
+//! the existing [`rep`](crate::rep) reputation changes.
+
+use crate::{config::PROPAGATE_TIMEOUT, rep};
+use codec::{Decode, Encode};
+use futures::{prelude::*, stream};
+use sc_network::utils::interval;
+use sc_network_gossip::{
+	GossipEngine, MessageIntent, Network as GossipNetwork, Syncing as GossipSyncing,
+	ValidationResult as GossipValidationResult, Validator, ValidatorContext,
+};
+use sc_network_types::PeerId;
+use sp_runtime::traits::{Block as BlockT, Hash as HashT, Header as HeaderT};
+use sp_statement_store::{NetworkPriority, Statement, StatementSource, StatementStore, SubmitResult};
+use std::{marker::PhantomData, pin::Pin, sync::Arc};
+
+/// The single topic all statements are gossiped under.
+///
+/// `GossipEngine` tracks known messages and expiry per topic, so segregating statement channels
+/// (e.g. one topic per account) is a direct extension of this if a caller ever needs it; a single
+/// topic reproduces the existing broadcast-to-every-peer semantics.
+pub fn statements_topic<B: BlockT>() -> B::Hash {
+	<<B::Header as HeaderT>::Hashing as HashT>::hash(b"statements")
+}
+
+/// Submits incoming statements to a [`StatementStore`] and maps the result onto a gossip
+/// keep/discard decision and the corresponding reputation change.
+pub struct StatementsValidator<B, S> {
+	statement_store: Arc<dyn StatementStore>,
+	sync: Arc<S>,
+	_block: PhantomData<fn() -> B>,
+}
+
+impl<B: BlockT, S: sp_consensus::SyncOracle> StatementsValidator<B, S> {
+	/// Creates a new validator submitting statements to `statement_store`.
+	pub fn new(statement_store: Arc<dyn StatementStore>, sync: Arc<S>) -> Self {
+		Self { statement_store, sync, _block: PhantomData }
+	}
+}
+
+impl<B, S> Validator<B> for StatementsValidator<B, S>
+where
+	B: BlockT,
+	S: sp_consensus::SyncOracle + Send + Sync,
+{
+	fn validate(
+		&self,
+		context: &mut dyn ValidatorContext<B>,
+		sender: &PeerId,
+		mut data: &[u8],
+	) -> GossipValidationResult<B::Hash> {
+		// A single check here replaces the `is_major_syncing` guard that
+		// `StatementHandler::handle_notification_event` otherwise has to repeat at every call
+		// site.
+		if self.sync.is_major_syncing() {
+			return GossipValidationResult::Discard
+		}
+
+		let Ok(statement) = Statement::decode(&mut data) else {
+			return GossipValidationResult::Discard
+		};
+
+		// Forces the peer to wait for verification, refunded below once it completes; mirrors
+		// `StatementHandler::on_statements`.
+		context.report_peer(*sender, rep::ANY_STATEMENT);
+
+		match self.statement_store.submit(statement, StatementSource::Network) {
+			SubmitResult::New(NetworkPriority::High) => {
+				context.report_peer(*sender, rep::EXCELLENT_STATEMENT);
+				GossipValidationResult::ProcessAndKeep(statements_topic::<B>())
+			},
+			SubmitResult::New(NetworkPriority::Low) => {
+				context.report_peer(*sender, rep::GOOD_STATEMENT);
+				GossipValidationResult::ProcessAndKeep(statements_topic::<B>())
+			},
+			SubmitResult::Known => {
+				// `DUPLICATE_STATEMENT` is reserved for the same peer re-sending a statement it
+				// already sent while the first copy is still being imported (see
+				// `StatementHandler::on_statements`); a fresh peer telling us
+				// about a statement we already hold from elsewhere is ordinary and only refunded.
+				context.report_peer(*sender, rep::ANY_STATEMENT_REFUND);
+				GossipValidationResult::Discard
+			},
+			SubmitResult::KnownExpired | SubmitResult::Ignored => {
+				context.report_peer(*sender, rep::ANY_STATEMENT_REFUND);
+				GossipValidationResult::Discard
+			},
+			SubmitResult::Bad(_) => {
+				context.report_peer(*sender, rep::BAD_STATEMENT);
+				GossipValidationResult::Discard
+			},
+			SubmitResult::InternalError(_) => {
+				context.report_peer(*sender, rep::ANY_STATEMENT_REFUND);
+				GossipValidationResult::Discard
+			},
+		}
+	}
+
+	fn message_expired<'a>(&'a self) -> Box<dyn FnMut(B::Hash, &[u8]) -> bool + 'a> {
+		// A gossiped statement is worth re-announcing to newly connected peers only for as long
+		// as the local store still has it; once it has been pruned there is nothing to serve.
+		Box::new(move |_topic, mut data| match Statement::decode(&mut data) {
+			Ok(statement) => self
+				.statement_store
+				.statement(&statement.hash())
+				.ok()
+				.flatten()
+				.is_none(),
+			Err(_) => true,
+		})
+	}
+
+	fn message_allowed<'a>(
+		&'a self,
+	) -> Box<dyn FnMut(&PeerId, MessageIntent, &B::Hash, &[u8]) -> bool + 'a> {
+		Box::new(move |_who, _intent, _topic, _data| !self.sync.is_major_syncing())
+	}
+}
+
+/// Statement handler built on top of [`GossipEngine`] instead of a hand-rolled notification
+/// protocol; see the [module docs](self) for why one would pick this over
+/// [`StatementHandler`](crate::StatementHandler).
+pub struct GossipStatementHandler<B: BlockT> {
+	gossip_engine: GossipEngine<B>,
+	statement_store: Arc<dyn StatementStore>,
+	propagate_timeout: stream::Fuse<Pin<Box<dyn Stream<Item = ()> + Send>>>,
+}
+
+impl<B: BlockT> GossipStatementHandler<B> {
+	/// Builds a new handler, constructing the [`GossipEngine`] from `network`/`sync` and the
+	/// given protocol name, with [`StatementsValidator`] as its message validator.
+	pub fn new<N, S>(
+		network: N,
+		sync: Arc<S>,
+		protocol_name: sc_network::types::ProtocolName,
+		notification_service: Box<dyn sc_network::service::traits::NotificationService>,
+		statement_store: Arc<dyn StatementStore>,
+		metrics_registry: Option<&prometheus_endpoint::Registry>,
+	) -> Self
+	where
+		N: GossipNetwork<B> + Clone + Send + 'static,
+		S: GossipSyncing<B> + sp_consensus::SyncOracle + Send + Sync + 'static,
+	{
+		let validator =
+			Arc::new(StatementsValidator::new(statement_store.clone(), sync.clone()));
+		let gossip_engine = GossipEngine::new(
+			network,
+			sync,
+			notification_service,
+			protocol_name,
+			validator,
+			metrics_registry,
+		);
+
+		Self {
+			gossip_engine,
+			statement_store,
+			propagate_timeout: (Box::pin(interval(PROPAGATE_TIMEOUT))
+				as Pin<Box<dyn Stream<Item = ()> + Send>>)
+				.fuse(),
+		}
+	}
+
+	/// Runs the handler forever: drives the [`GossipEngine`] and periodically re-announces the
+	/// local statement set on the shared topic so that fresh peers pick it up (the engine itself
+	/// handles de-duplication against each peer's known-message set).
+	pub async fn run(mut self) {
+		loop {
+			futures::select! {
+				_ = self.propagate_timeout.next() => {
+					self.announce_statements();
+				},
+				_ = (&mut self.gossip_engine).fuse() => {
+					// `GossipEngine` only resolves when the underlying network service has shut
+					// down; there's nothing left to drive.
+					return
+				},
+			}
+		}
+	}
+
+	fn announce_statements(&mut self) {
+		let Ok(statements) = self.statement_store.statements() else { return };
+		let topic = statements_topic::<B>();
+		for (_, statement) in statements {
+			self.gossip_engine.gossip_message(topic, statement.encode(), false);
+		}
+	}
+}
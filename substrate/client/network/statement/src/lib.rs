@@ -21,10 +21,20 @@
 //! Usage:
 //!
 //! - Use [`StatementHandlerPrototype::new`] to create a prototype.
-//! - Pass the `NonDefaultSetConfig` returned from [`StatementHandlerPrototype::new`] to the network
-//!   configuration as an extra peers set.
+//! - Pass the `NonDefaultSetConfig` and the two `RequestResponseConfig`s returned from
+//!   [`StatementHandlerPrototype::new`] to the network configuration as, respectively, an extra
+//!   peers set and two extra request/response protocols.
 //! - Use [`StatementHandlerPrototype::build`] then [`StatementHandler::run`] to obtain a
-//! `Future` that processes statements.
+//! `Future` that processes statements, together with a [`StatementRequester`] that callers other
+//! than the handler (RPC, light-client logic, ...) can use to pull statements on demand.
+//!
+//! Fresh statements are still disseminated eagerly over the notification protocol, as before; the
+//! `/statement/sync/1` request/response protocol (see the [`reconciliation`] module) is used
+//! alongside it to periodically close any gaps left by dropped notifications or missed gossip
+//! windows, without resorting to repeatedly flooding the whole statement set. The
+//! `/statement/request/1` protocol (see the [`request`] module) instead serves one-off, caller
+//! driven pulls, and is also what the handler itself uses to catch up on whatever gossip ignored
+//! while major syncing was in progress.
 
 use crate::config::*;
 
@@ -42,7 +52,7 @@ use sc_network::{
 	},
 	types::ProtocolName,
 	utils::{interval, LruHashSet},
-	NetworkBackend, NetworkEventStream, NetworkPeers,
+	NetworkBackend, NetworkEventStream, NetworkPeers, NetworkRequest,
 };
 use sc_network_common::role::ObservedRole;
 use sc_network_sync::{SyncEvent, SyncEventStream};
@@ -60,6 +70,21 @@ use std::{
 };
 
 pub mod config;
+#[cfg(feature = "gossip-statement-handler")]
+mod gossip;
+mod import_queue;
+mod reconciliation;
+mod request;
+
+#[cfg(feature = "gossip-statement-handler")]
+pub use gossip::GossipStatementHandler;
+pub use import_queue::DEFAULT_WORKERS;
+pub use reconciliation::{
+	reconciliation_protocol_config, ReconciliationRequest, ReconciliationResponse,
+};
+pub use request::{
+	request_protocol_config, StatementRequest, StatementRequester, StatementResponse,
+};
 
 /// A set of statements.
 pub type Statements = Vec<Statement>;
@@ -109,10 +134,20 @@ impl Metrics {
 pub struct StatementHandlerPrototype {
 	protocol_name: ProtocolName,
 	notification_service: Box<dyn NotificationService>,
+	reconciliation_protocol_name: ProtocolName,
+	reconciliation_inbound_queue:
+		async_channel::Receiver<sc_network::request_responses::IncomingRequest>,
+	request_protocol_name: ProtocolName,
+	request_inbound_queue: async_channel::Receiver<sc_network::request_responses::IncomingRequest>,
 }
 
 impl StatementHandlerPrototype {
 	/// Create a new instance.
+	///
+	/// Besides the notification protocol config used for the existing gossip-based
+	/// dissemination, this also returns the request/response protocol configs for the
+	/// `/statement/sync/1` set-reconciliation protocol and the `/statement/request/1` on-demand
+	/// fetch protocol; all three must be passed to the network configuration as extra protocols.
 	pub fn new<
 		Hash: AsRef<[u8]>,
 		Block: BlockT,
@@ -122,7 +157,12 @@ impl StatementHandlerPrototype {
 		fork_id: Option<&str>,
 		metrics: NotificationMetrics,
 		peer_store_handle: Arc<dyn PeerStoreProvider>,
-	) -> (Self, Net::NotificationProtocolConfig) {
+	) -> (
+		Self,
+		Net::NotificationProtocolConfig,
+		sc_network::config::RequestResponseConfig,
+		sc_network::config::RequestResponseConfig,
+	) {
 		let genesis_hash = genesis_hash.as_ref();
 		let protocol_name = if let Some(fork_id) = fork_id {
 			format!("/{}/{}/statement/1", array_bytes::bytes2hex("", genesis_hash), fork_id)
@@ -144,64 +184,98 @@ impl StatementHandlerPrototype {
 			peer_store_handle,
 		);
 
-		(Self { protocol_name: protocol_name.into(), notification_service }, config)
+		let (reconciliation_config, reconciliation_inbound_queue) =
+			reconciliation::reconciliation_protocol_config(genesis_hash, fork_id);
+		let reconciliation_protocol_name = reconciliation_config.name.clone();
+
+		let (request_config, request_inbound_queue) =
+			request::request_protocol_config(genesis_hash, fork_id);
+		let request_protocol_name = request_config.name.clone();
+
+		(
+			Self {
+				protocol_name: protocol_name.into(),
+				notification_service,
+				reconciliation_protocol_name,
+				reconciliation_inbound_queue,
+				request_protocol_name,
+				request_inbound_queue,
+			},
+			config,
+			reconciliation_config,
+			request_config,
+		)
 	}
 
-	/// Turns the prototype into the actual handler.
+	/// Turns the prototype into the actual handler, together with a [`StatementRequester`] that
+	/// RPC or light-client logic can use to pull statements from a specific peer instead of
+	/// waiting on push gossip.
 	///
 	/// Important: the statements handler is initially disabled and doesn't gossip statements.
 	/// Gossiping is enabled when major syncing is done.
+	///
+	/// `import_queue_workers` sets how many concurrent tasks validate and submit incoming
+	/// statements (see [`import_queue`]); pass [`DEFAULT_WORKERS`] absent a more
+	/// specific preference.
 	pub fn build<
-		N: NetworkPeers + NetworkEventStream,
+		N: NetworkPeers + NetworkEventStream + NetworkRequest + Clone,
 		S: SyncEventStream + sp_consensus::SyncOracle,
 	>(
 		self,
 		network: N,
 		sync: S,
 		statement_store: Arc<dyn StatementStore>,
+		import_queue_workers: usize,
 		metrics_registry: Option<&Registry>,
 		executor: impl Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send,
-	) -> error::Result<StatementHandler<N, S>> {
+	) -> error::Result<(StatementHandler<N, S>, StatementRequester<N>)> {
 		let sync_event_stream = sync.event_stream("statement-handler-sync");
-		let (queue_sender, mut queue_receiver) = async_channel::bounded(100_000);
 
-		let store = statement_store.clone();
+		let import_queue_metrics = metrics_registry
+			.map(import_queue::ImportQueueMetrics::register)
+			.transpose()?;
+		let import_queue = import_queue::StatementImportQueue::new(
+			statement_store.clone(),
+			import_queue_workers,
+			import_queue_metrics,
+			&executor,
+		);
+
 		executor(
-			async move {
-				loop {
-					let task: Option<(Statement, oneshot::Sender<SubmitResult>)> =
-						queue_receiver.next().await;
-					match task {
-						None => return,
-						Some((statement, completion)) => {
-							let result = store.submit(statement, StatementSource::Network);
-							if completion.send(result).is_err() {
-								log::debug!(
-									target: LOG_TARGET,
-									"Error sending validation completion"
-								);
-							}
-						},
-					}
-				}
-			}
+			reconciliation::run_reconciliation_server(
+				self.reconciliation_inbound_queue,
+				statement_store.clone(),
+			)
 			.boxed(),
 		);
+		executor(
+			request::run_request_server(self.request_inbound_queue, statement_store.clone())
+				.boxed(),
+		);
+
+		let requester = StatementRequester::new(network.clone(), self.request_protocol_name);
 
 		let handler = StatementHandler {
 			protocol_name: self.protocol_name,
 			notification_service: self.notification_service,
+			reconciliation_protocol_name: self.reconciliation_protocol_name,
+			reconcile_timeout: (Box::pin(interval(reconciliation::RECONCILE_TIMEOUT))
+				as Pin<Box<dyn Stream<Item = ()> + Send>>)
+				.fuse(),
+			reconciliations: FuturesUnordered::new(),
 			propagate_timeout: (Box::pin(interval(PROPAGATE_TIMEOUT))
 				as Pin<Box<dyn Stream<Item = ()> + Send>>)
 				.fuse(),
 			pending_statements: FuturesUnordered::new(),
 			pending_statements_peers: HashMap::new(),
+			was_major_syncing: sync.is_major_syncing(),
+			requester: requester.clone(),
 			network,
 			sync,
 			sync_event_stream: sync_event_stream.fuse(),
 			peers: HashMap::new(),
 			statement_store,
-			queue_sender,
+			import_queue,
 			metrics: if let Some(r) = metrics_registry {
 				Some(Metrics::register(r)?)
 			} else {
@@ -209,7 +283,60 @@ impl StatementHandlerPrototype {
 			},
 		};
 
-		Ok(handler)
+		Ok((handler, requester))
+	}
+
+	/// Alternative to [`Self::build`]: turns the prototype into a
+	/// [`GossipStatementHandler`](gossip::GossipStatementHandler) built on top of
+	/// `sc-network-gossip`'s `GossipEngine`/`Validator` instead of hand-rolled peer bookkeeping.
+	///
+	/// Use this when the node already pulls in `sc-network-gossip` for other protocols (e.g.
+	/// consensus gossip) and would rather reuse its message-expiry, per-topic tracking and
+	/// connection/role management than duplicate it here. Statement gossiping is still gated on
+	/// major syncing, but that check now lives entirely in
+	/// [`StatementsValidator`](gossip::StatementsValidator).
+	///
+	/// Requires the `gossip-statement-handler` feature, which in turn requires `sc-network-gossip`
+	/// to be declared as a dependency of this crate; it is kept optional so that callers who only
+	/// want [`Self::build`]'s hand-rolled notification path don't pay for pulling it in.
+	#[cfg(feature = "gossip-statement-handler")]
+	pub fn build_with_gossip_engine<B, N, S>(
+		self,
+		network: N,
+		sync: Arc<S>,
+		statement_store: Arc<dyn StatementStore>,
+		metrics_registry: Option<&Registry>,
+		executor: impl Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send,
+	) -> (gossip::GossipStatementHandler<B>, StatementRequester<N>)
+	where
+		B: BlockT,
+		N: sc_network_gossip::Network<B> + NetworkRequest + Clone + Send + 'static,
+		S: sc_network_gossip::Syncing<B> + sp_consensus::SyncOracle + Send + Sync + 'static,
+	{
+		executor(
+			reconciliation::run_reconciliation_server(
+				self.reconciliation_inbound_queue,
+				statement_store.clone(),
+			)
+			.boxed(),
+		);
+		executor(
+			request::run_request_server(self.request_inbound_queue, statement_store.clone())
+				.boxed(),
+		);
+
+		let requester = StatementRequester::new(network.clone(), self.request_protocol_name);
+
+		let handler = gossip::GossipStatementHandler::new(
+			network,
+			sync,
+			self.protocol_name,
+			self.notification_service,
+			statement_store,
+			metrics_registry,
+		);
+
+		(handler, requester)
 	}
 }
 
@@ -219,8 +346,14 @@ pub struct StatementHandler<
 	S: SyncEventStream + sp_consensus::SyncOracle,
 > {
 	protocol_name: ProtocolName,
+	/// Name of the `/statement/sync/1` request/response protocol.
+	reconciliation_protocol_name: ProtocolName,
 	/// Interval at which we call `propagate_statements`.
 	propagate_timeout: stream::Fuse<Pin<Box<dyn Stream<Item = ()> + Send>>>,
+	/// Interval at which we initiate a reconciliation round with one peer.
+	reconcile_timeout: stream::Fuse<Pin<Box<dyn Stream<Item = ()> + Send>>>,
+	/// Reconciliation rounds currently in flight.
+	reconciliations: FuturesUnordered<Pin<Box<dyn Future<Output = ()> + Send>>>,
 	/// Pending statements verification tasks.
 	pending_statements:
 		FuturesUnordered<Pin<Box<dyn Future<Output = (Hash, Option<SubmitResult>)> + Send>>>,
@@ -229,6 +362,13 @@ pub struct StatementHandler<
 	/// imported. This prevents that we import the same statement
 	/// multiple times concurrently.
 	pending_statements_peers: HashMap<Hash, HashSet<PeerId>>,
+	/// Whether `sync` was reporting major syncing the last time we checked; used to detect the
+	/// moment syncing finishes so we can issue a catch-up [`StatementRequest::Since`] for
+	/// whatever gossip ignored in the meantime.
+	was_major_syncing: bool,
+	/// Handle for issuing on-demand `/statement/request/1` requests, also used internally for the
+	/// post-sync catch-up request.
+	requester: StatementRequester<N>,
 	/// Network service to use to send messages and manage peers.
 	network: N,
 	/// Syncing service.
@@ -240,7 +380,7 @@ pub struct StatementHandler<
 	// All connected peers
 	peers: HashMap<PeerId, Peer>,
 	statement_store: Arc<dyn StatementStore>,
-	queue_sender: async_channel::Sender<(Statement, oneshot::Sender<SubmitResult>)>,
+	import_queue: import_queue::StatementImportQueue,
 	/// Prometheus metrics.
 	metrics: Option<Metrics>,
 }
@@ -255,7 +395,7 @@ struct Peer {
 
 impl<N, S> StatementHandler<N, S>
 where
-	N: NetworkPeers + NetworkEventStream,
+	N: NetworkPeers + NetworkEventStream + NetworkRequest + Clone + Send + Sync + 'static,
 	S: SyncEventStream + sp_consensus::SyncOracle,
 {
 	/// Turns the [`StatementHandler`] into a future that should run forever and not be
@@ -264,8 +404,13 @@ where
 		loop {
 			futures::select! {
 				_ = self.propagate_timeout.next() => {
+					self.check_sync_transition();
 					self.propagate_statements();
 				},
+				_ = self.reconcile_timeout.next() => {
+					self.start_reconciliation();
+				},
+				() = self.reconciliations.select_next_some() => {},
 				(hash, result) = self.pending_statements.select_next_some() => {
 					if let Some(peers) = self.pending_statements_peers.remove(&hash) {
 						if let Some(result) = result {
@@ -295,6 +440,101 @@ where
 		}
 	}
 
+	/// Picks a peer to reconcile with and spawns the reconciliation round as a future polled
+	/// alongside everything else in [`Self::run`].
+	fn start_reconciliation(&mut self) {
+		if self.sync.is_major_syncing() {
+			return
+		}
+
+		let Some(who) = self
+			.peers
+			.iter()
+			.find(|(_, peer)| !matches!(peer.role, ObservedRole::Light))
+			.map(|(who, _)| *who)
+		else {
+			return
+		};
+
+		log::trace!(target: LOG_TARGET, "Starting statement reconciliation with {who}");
+		let network = self.network.clone();
+		let protocol_name = self.reconciliation_protocol_name.clone();
+		let statement_store = self.statement_store.clone();
+		self.reconciliations.push(
+			async move {
+				if let Err(rep) =
+					reconciliation::reconcile_with_peer(&network, protocol_name, who, &statement_store)
+						.await
+				{
+					network.report_peer(who, rep);
+				}
+			}
+			.boxed(),
+		);
+	}
+
+	/// Detects the moment major syncing finishes and, if so, issues a catch-up
+	/// [`StatementRequest::Since`] to backfill whatever gossip ignored while it was syncing.
+	fn check_sync_transition(&mut self) {
+		let is_major_syncing = self.sync.is_major_syncing();
+		if self.was_major_syncing && !is_major_syncing {
+			self.start_catch_up();
+		}
+		self.was_major_syncing = is_major_syncing;
+	}
+
+	/// Requests everything a peer has that we don't, via [`StatementRequester::request_since`],
+	/// and submits whatever comes back to the local store.
+	///
+	/// Targets a connected, non-light reserved peer rather than an arbitrary connected one, since
+	/// that peer alone decides which statements come back. Each returned statement is still
+	/// submitted to the store, which verifies it as it does any other network statement.
+	fn start_catch_up(&mut self) {
+		let network = self.network.clone();
+		let connected_non_light: HashSet<PeerId> = self
+			.peers
+			.iter()
+			.filter(|(_, peer)| !matches!(peer.role, ObservedRole::Light))
+			.map(|(who, _)| *who)
+			.collect();
+		let requester = self.requester.clone();
+		let statement_store = self.statement_store.clone();
+		self.reconciliations.push(
+			async move {
+				let Some(who) = network
+					.reserved_peers()
+					.await
+					.unwrap_or_default()
+					.into_iter()
+					.find(|who| connected_non_light.contains(who))
+				else {
+					log::debug!(
+						target: LOG_TARGET,
+						"Major sync finished, but no reserved peer is connected to catch up from",
+					);
+					return
+				};
+
+				log::debug!(target: LOG_TARGET, "Major sync finished, requesting catch-up statements from {who}");
+				let already_known = statement_store
+					.statements()
+					.map(|statements| statements.into_iter().map(|(hash, _)| hash).collect())
+					.unwrap_or_default();
+				match requester.request_since(who, already_known).await {
+					Ok(statements) =>
+						for statement in statements {
+							let _ = statement_store.submit(statement, StatementSource::Network);
+						},
+					Err(()) => log::debug!(
+						target: LOG_TARGET,
+						"Catch-up statement request to {who} failed",
+					),
+				}
+			}
+			.boxed(),
+		);
+	}
+
 	fn handle_sync_event(&mut self, event: SyncEvent) {
 		match event {
 			SyncEvent::InitialPeers(peer_ids) => {
@@ -400,32 +640,27 @@ where
 				self.network.report_peer(who, rep::ANY_STATEMENT);
 
 				match self.pending_statements_peers.entry(hash) {
-					Entry::Vacant(entry) => {
-						let (completion_sender, completion_receiver) = oneshot::channel();
-						match self.queue_sender.try_send((s, completion_sender)) {
-							Ok(()) => {
-								self.pending_statements.push(
-									async move {
-										let res = completion_receiver.await;
-										(hash, res.ok())
-									}
-									.boxed(),
-								);
-								entry.insert(HashSet::from_iter([who]));
-							},
-							Err(async_channel::TrySendError::Full(_)) => {
-								log::debug!(
-									target: LOG_TARGET,
-									"Dropped statement because validation channel is full",
-								);
-							},
-							Err(async_channel::TrySendError::Closed(_)) => {
-								log::trace!(
-									target: LOG_TARGET,
-									"Dropped statement because validation channel is closed",
-								);
-							},
-						}
+					Entry::Vacant(entry) => match self.import_queue.submit(s) {
+						Ok(completion_receiver) => {
+							self.pending_statements.push(
+								async move {
+									let res = completion_receiver.await;
+									(hash, res.ok())
+								}
+								.boxed(),
+							);
+							entry.insert(HashSet::from_iter([who]));
+						},
+						Err(_statement) => {
+							log::debug!(
+								target: LOG_TARGET,
+								"Dropped statement because the import queue is full, refunding {who}",
+							);
+							// The statement was never actually judged good or bad, so undo the
+							// verification penalty applied above instead of letting backpressure
+							// look like a bad statement.
+							self.network.report_peer(who, rep::ANY_STATEMENT_REFUND);
+						},
 					},
 					Entry::Occupied(mut entry) => {
 						if !entry.get_mut().insert(who) {
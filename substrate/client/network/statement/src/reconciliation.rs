@@ -0,0 +1,837 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Pull-based statement set reconciliation (Erlay-style).
+//!
+//! Instead of flooding the whole local statement set to every peer on each propagation tick,
+//! a node periodically reconciles its set with a single peer at a time over the
+//! `/statement/sync/1` request/response protocol:
+//!
+//! 1. The initiator asks the peer for an approximate count of the statements it holds, which is
+//!    used together with the local count to estimate the size `d` of the symmetric difference.
+//! 2. The initiator builds a [`StatementSketch`] of capacity `c >= d` over the short IDs
+//!    ([`ShortStatementId`]) of its own statements and sends it to the peer.
+//! 3. The peer merges (XORs) the received sketch with its own sketch of the same capacity, which
+//!    leaves exactly the sketch of the symmetric difference, and decodes it to recover the
+//!    differing short IDs. For each recovered ID the peer already knows, by checking its own set,
+//!    whether it needs to push it to the initiator or ask the initiator for it.
+//! 4. The peer replies with the statements the initiator is missing and the list of short IDs it
+//!    would like in return; the initiator then pushes those as a follow-up request.
+//!
+//! Short IDs are a truncation of [`Statement::hash`] and are only ever used to agree on *which*
+//! statements differ; the full statement (and its hash) is always re-verified once received, so a
+//! short ID collision can never cause an incorrect import. It can cause a statement to be sent
+//! that the peer already holds, or, when both sides hold different statements with the same short
+//! ID, neither of the two to be found in that round, since their IDs cancel out in the sketch.
+
+use crate::{Statements, LOG_TARGET};
+use codec::{Decode, Encode};
+use futures::prelude::*;
+use sc_network::{
+	config::RequestResponseConfig,
+	request_responses::{IncomingRequest, OutgoingResponse},
+	types::ProtocolName,
+	IfDisconnected, NetworkRequest, ReputationChange,
+};
+use sc_network_types::PeerId;
+use sp_statement_store::{Hash, Statement, StatementSource, StatementStore};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+/// A truncated [`Statement::hash`] used to cheaply agree on set membership with a peer.
+pub type ShortStatementId = u32;
+
+/// How often a node initiates reconciliation with a peer.
+pub const RECONCILE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Initial sketch capacity used when no prior size estimate is available.
+const DEFAULT_CAPACITY: u32 = 16;
+
+/// Extra slack added on top of the estimated symmetric difference size, to absorb the fact that
+/// the size estimate is only approximate.
+const CAPACITY_SLACK: u32 = 8;
+
+/// Upper bound on the sketch capacity before giving up and falling back to a bounded full flood.
+const MAX_CAPACITY: u32 = 4096;
+
+/// Reputation change for a peer that reports it could not decode our sketch even at
+/// [`MAX_CAPACITY`].
+pub const BAD_SKETCH: ReputationChange = ReputationChange::new(-(1 << 10), "Bad statement sketch");
+
+/// Truncates a statement hash down to a [`ShortStatementId`].
+///
+/// This is a simple truncation rather than a fresh hash: the input is already a cryptographic
+/// hash, so its low bits are as uniformly distributed as a dedicated short-hash would be.
+pub fn short_id(hash: &Hash) -> ShortStatementId {
+	u32::from_le_bytes([hash[0], hash[1], hash[2], hash[3]])
+}
+
+/// Returns the protocol name for the statement reconciliation request/response protocol.
+pub fn reconciliation_protocol_name<Hash: AsRef<[u8]>>(
+	genesis_hash: Hash,
+	fork_id: Option<&str>,
+) -> ProtocolName {
+	let genesis_hash = genesis_hash.as_ref();
+	if let Some(fork_id) = fork_id {
+		format!(
+			"/{}/{}/statement/sync/1",
+			array_bytes::bytes2hex("", genesis_hash),
+			fork_id
+		)
+	} else {
+		format!(
+			"/{}/statement/sync/1",
+			array_bytes::bytes2hex("", genesis_hash)
+		)
+	}
+	.into()
+}
+
+/// A sketch of a set of [`ShortStatementId`]s that allows two peers to recover the symmetric
+/// difference of their sets without exchanging the sets themselves.
+///
+/// The sketch of a set `S` is the vector of odd power sums `sum_{e in S} e^(2k-1)` for
+/// `k = 1..capacity`, computed in `GF(2^32)`. Sketches are linear: the sketch of the symmetric
+/// difference of two sets equals the (coordinate-wise, in-field) XOR of their individual
+/// sketches, which is exactly what [`StatementSketch::merge`] computes.
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
+pub struct StatementSketch {
+	capacity: u32,
+	sums: Vec<u64>,
+}
+
+impl StatementSketch {
+	/// Builds a sketch of the given `capacity` over `ids`.
+	pub fn build(ids: impl IntoIterator<Item = ShortStatementId>, capacity: u32) -> Self {
+		let mut sums = vec![0u64; capacity as usize];
+		for id in ids {
+			let mut power = gf_reduce(id as u64);
+			let square = gf_mul(power, power);
+			for sum in sums.iter_mut() {
+				*sum = gf_add(*sum, power);
+				power = gf_mul(power, square);
+			}
+		}
+		Self { capacity, sums }
+	}
+
+	/// The capacity this sketch was built with.
+	pub fn capacity(&self) -> u32 {
+		self.capacity
+	}
+
+	/// Merges `other` into `self`, turning `self` into the sketch of the symmetric difference of
+	/// the two original sets. Both sketches must have been built with the same capacity.
+	pub fn merge(&mut self, other: &StatementSketch) {
+		debug_assert_eq!(
+			self.capacity, other.capacity,
+			"merging sketches of different capacity"
+		);
+		for (a, b) in self.sums.iter_mut().zip(other.sums.iter()) {
+			*a = gf_add(*a, *b);
+		}
+	}
+
+	/// Attempts to decode this sketch (expected to be the sketch of a symmetric difference) into
+	/// the set of differing short IDs.
+	///
+	/// Returns `None` if the true difference exceeds the sketch's capacity and decoding is not
+	/// possible; the caller should retry with a larger capacity or fall back to a full flood. A
+	/// capacity too small for the true difference can, in rare cases, still produce *some*
+	/// locator that fully factors into distinct roots; the rebuild-and-compare below catches
+	/// those by checking the decoded set actually reproduces `self.sums`, rather than just that
+	/// Berlekamp-Massey and root-finding both nominally succeeded. If a different set of at most
+	/// `capacity` IDs has exactly the same sketch as the true difference, that set is returned:
+	/// the sketch alone cannot tell the two apart.
+	pub fn decode(&self) -> Option<Vec<ShortStatementId>> {
+		let syndromes = full_syndromes(&self.sums);
+		let locator = berlekamp_massey(&syndromes);
+		let difference = find_roots(&locator)?;
+		let rebuilt = Self::build(difference.iter().copied(), self.capacity);
+		(rebuilt.sums == self.sums).then_some(difference)
+	}
+}
+
+/// Picks a sketch capacity for a symmetric difference estimated at `estimated_diff`, capped at
+/// [`MAX_CAPACITY`].
+pub fn capacity_for_estimate(estimated_diff: u32) -> u32 {
+	estimated_diff
+		.saturating_add(CAPACITY_SLACK)
+		.max(DEFAULT_CAPACITY)
+		.min(MAX_CAPACITY)
+}
+
+/// Estimates the size of the symmetric difference from the two (approximate) set sizes.
+///
+/// This is only a lower bound: two sets of equal size can still differ arbitrarily, but it is a
+/// reasonable starting point for picking the first sketch capacity, and is refined by retrying
+/// with a bigger capacity on decode failure.
+pub fn estimate_diff(our_size: u32, their_size: u32) -> u32 {
+	our_size.abs_diff(their_size)
+}
+
+/// Request sent to a peer to initiate or continue a reconciliation round.
+#[derive(Debug, Encode, Decode)]
+pub enum ReconciliationRequest {
+	/// Ask the peer for the approximate number of statements it holds.
+	EstimateSize,
+	/// Send a sketch of our statement set for the peer to merge with its own and decode.
+	Sketch {
+		/// Capacity the sketch was built with.
+		capacity: u32,
+		/// The sketch itself.
+		sketch: StatementSketch,
+	},
+	/// Push statements the peer previously told us it was missing.
+	Push {
+		/// The statements the peer requested.
+		statements: Statements,
+	},
+}
+
+/// Response to a [`ReconciliationRequest`].
+#[derive(Debug, Encode, Decode)]
+pub enum ReconciliationResponse {
+	/// Answer to [`ReconciliationRequest::EstimateSize`].
+	Size {
+		/// Approximate number of statements held by the responder.
+		len: u32,
+	},
+	/// Answer to [`ReconciliationRequest::Sketch`] when decoding succeeded.
+	Reconciled {
+		/// Statements the initiator is missing, pushed eagerly since the responder already has
+		/// them at hand.
+		send_to_you: Statements,
+		/// Short IDs the responder would like the initiator to [`ReconciliationRequest::Push`]
+		/// back.
+		request_from_you: Vec<ShortStatementId>,
+	},
+	/// Decoding the sketch failed; the initiator should retry with a larger capacity, up to
+	/// [`MAX_CAPACITY`], or fall back to a bounded full flood.
+	DecodeFailed,
+	/// Acknowledges a [`ReconciliationRequest::Push`].
+	Ack,
+}
+
+/// Given the locally held `(hash, statement)` pairs and the short IDs the peer reported missing
+/// or offering, classifies a decoded symmetric difference into "statements we should push to the
+/// peer" and "short IDs we should ask the peer for".
+///
+/// `local` is indexed by short ID to tolerate (and verify away) short-ID collisions: if more than
+/// one local statement truncates to the same short ID the peer should receive all of them, the
+/// full hash re-verification on the other end will discard whichever it already has.
+pub fn classify_difference(
+	local: &HashMap<ShortStatementId, Vec<(Hash, Statement)>>,
+	difference: &[ShortStatementId],
+) -> (Statements, Vec<ShortStatementId>) {
+	let mut send_to_you = Vec::new();
+	let mut request_from_you = Vec::new();
+	for id in difference {
+		match local.get(id) {
+			Some(statements) => send_to_you.extend(statements.iter().map(|(_, s)| s.clone())),
+			None => request_from_you.push(*id),
+		}
+	}
+	(send_to_you, request_from_you)
+}
+
+/// Returns the reputation change for a peer that could not decode our sketch even at
+/// [`MAX_CAPACITY`], and logs it.
+pub fn on_decode_failure(peer: &PeerId) -> ReputationChange {
+	log::debug!(
+		target: LOG_TARGET,
+		"{peer}: statement sketch failed to decode at maximum capacity, reporting",
+	);
+	BAD_SKETCH
+}
+
+/// Whether `capacity` has reached [`MAX_CAPACITY`] and retrying with a larger sketch is pointless;
+/// callers should fall back to a bounded full flood instead. The receiving side submits pushed
+/// statements to its store without applying any per-statement reputation change.
+pub fn should_fall_back_to_flood(capacity: u32) -> bool {
+	capacity >= MAX_CAPACITY
+}
+
+// --- GF(2^32) arithmetic -----------------------------------------------------------------
+
+/// Irreducible polynomial defining `GF(2^32)` (x^32 + x^7 + x^3 + x^2 + 1), used for field
+/// reduction during multiplication.
+const GF_MODULUS: u64 = 0x1_0000_008D;
+
+fn gf_add(a: u64, b: u64) -> u64 {
+	a ^ b
+}
+
+/// Carry-less multiplication of two field elements followed by reduction modulo [`GF_MODULUS`].
+fn gf_mul(mut a: u64, mut b: u64) -> u64 {
+	let mut result = 0u64;
+	while b != 0 {
+		if b & 1 != 0 {
+			result ^= a;
+		}
+		a <<= 1;
+		if a & (1 << 32) != 0 {
+			a ^= GF_MODULUS;
+		}
+		b >>= 1;
+	}
+	result
+}
+
+/// Reduces an arbitrary `u64` into the field; short IDs are already < 2^32 so this is a no-op,
+/// kept for clarity at call sites.
+fn gf_reduce(x: u64) -> u64 {
+	x & 0xFFFF_FFFF
+}
+
+fn gf_inv(a: u64) -> u64 {
+	// GF(2^32)* has order 2^32 - 1, so a^(2^32 - 2) = a^-1 for a != 0.
+	let mut result = 1u64;
+	let mut base = a;
+	let mut exp = (1u64 << 32) - 2;
+	while exp != 0 {
+		if exp & 1 != 0 {
+			result = gf_mul(result, base);
+		}
+		base = gf_mul(base, base);
+		exp >>= 1;
+	}
+	result
+}
+
+/// Reconstructs the full syndrome sequence `s_1, s_2, ..., s_(2*odd.len())` from the odd power
+/// sums `odd[k] = s_(2k+1)` stored in a [`StatementSketch`].
+///
+/// Only the odd syndromes are ever transmitted, since in `GF(2^32)` (characteristic 2) squaring
+/// is a field automorphism (the Frobenius map), so `s_k^2 = sum_{e in S} e^(2k) = s_(2k)`: every
+/// even syndrome is already determined by a lower odd one and carries no extra information.
+/// Berlekamp-Massey, however, needs the full consecutive sequence to treat `sums` as syndromes of
+/// an error-locator polynomial, so the even entries are filled back in here before decoding.
+fn full_syndromes(odd: &[u64]) -> Vec<u64> {
+	let mut syndromes = vec![0u64; odd.len() * 2];
+	for i in 1..=syndromes.len() {
+		syndromes[i - 1] = if i % 2 == 1 {
+			odd[(i - 1) / 2]
+		} else {
+			gf_mul(syndromes[i / 2 - 1], syndromes[i / 2 - 1])
+		};
+	}
+	syndromes
+}
+
+/// Runs the Berlekamp-Massey algorithm over `GF(2^32)` on the power-sum sequence `syndromes`,
+/// returning the shortest-degree locator polynomial consistent with it (lowest-degree coefficient
+/// first, constant term normalized to `1`).
+///
+/// Berlekamp-Massey itself always produces *some* locator, whether or not the true symmetric
+/// difference actually fits within `syndromes.len() / 2` terms — it does not, by itself, detect a
+/// too-small capacity. That check happens downstream, in [`find_roots`] (degree and
+/// squarefreeness) and [`StatementSketch::decode`]'s rebuild-and-compare, so this returns the
+/// locator unconditionally rather than an `Option`.
+fn berlekamp_massey(syndromes: &[u64]) -> Vec<u64> {
+	let n = syndromes.len();
+	let mut locator = vec![1u64];
+	let mut prev_locator = vec![1u64];
+	let mut prev_discrepancy = 1u64;
+	let mut shift = 1usize;
+
+	for i in 0..n {
+		let mut discrepancy = syndromes[i];
+		for (j, coeff) in locator.iter().enumerate().skip(1) {
+			discrepancy = gf_add(discrepancy, gf_mul(*coeff, syndromes[i - j]));
+		}
+
+		if discrepancy == 0 {
+			shift += 1;
+			continue;
+		}
+
+		let scale = gf_mul(discrepancy, gf_inv(prev_discrepancy));
+		let mut candidate = locator.clone();
+		candidate.resize(candidate.len().max(prev_locator.len() + shift), 0);
+		for (j, coeff) in prev_locator.iter().enumerate() {
+			candidate[j + shift] = gf_add(candidate[j + shift], gf_mul(scale, *coeff));
+		}
+
+		if 2 * (locator.len() - 1) <= i {
+			prev_locator = locator;
+			prev_discrepancy = discrepancy;
+			shift = 1;
+		} else {
+			shift += 1;
+		}
+		locator = candidate;
+	}
+
+	locator
+}
+
+/// Finds the short IDs located by the error-locator polynomial, returning `None` if it does not
+/// split into `degree` distinct roots in `GF(2^32)` (a sign that decoding failed, usually because
+/// the sketch capacity was exceeded).
+///
+/// As is standard for this kind of syndrome decoding, `locator`'s roots are the *reciprocals* of
+/// the differing short IDs (`locator(x) = prod (1 - id*x)`), not the short IDs themselves; each
+/// root found by [`split_into_linear_factors`] is inverted back into a short ID before returning.
+fn find_roots(locator: &[u64]) -> Option<Vec<ShortStatementId>> {
+	let locator = poly_trim(locator.to_vec());
+	let degree = poly_degree(&locator);
+	if degree == 0 {
+		return Some(Vec::new());
+	}
+
+	// A polynomial with repeated roots has a nonzero GCD with its own formal derivative; that
+	// can only happen here if the capacity was too small for the true difference, in which case
+	// there is no meaningful set of distinct short IDs to recover.
+	if poly_degree(&poly_gcd(&locator, &poly_derivative(&locator))) > 0 {
+		return None;
+	}
+
+	let roots = split_into_linear_factors(&locator)?;
+	(roots.len() == degree).then_some(roots)
+}
+
+/// Recursively splits `poly` (assumed to divide `x^(2^32) - x`, i.e. to split completely into
+/// linear factors over `GF(2^32)`) into the inverses of its roots (see [`find_roots`]).
+///
+/// Uses the characteristic-2 analogue of Cantor-Zassenhaus equal-degree splitting: for a scalar
+/// `a`, the trace-to-`GF(2)` polynomial `Tr(a*x) = sum_(i=0..31) (a*x)^(2^i) mod poly` partitions
+/// the roots of `poly` into two (usually non-trivial) halves according to whether it evaluates to
+/// `0` or `1` at each root, which `gcd(poly, Tr(a*x))` / `gcd(poly, Tr(a*x) + 1)` recover directly
+/// without ever evaluating `poly` itself at a candidate root. Degree-2 and up recurse; degree 1
+/// is solved directly. Returns `None` if `poly` does not actually split into distinct linear
+/// factors (some other decode failure), which shows up as repeated failures to find a non-trivial
+/// split within [`MAX_SPLIT_ATTEMPTS`].
+///
+/// `a` is drawn from [`split_attempt_multiplier`] rather than tried as `1, 2, 3, ...`: short IDs
+/// (and so the roots derived from them) are truncated hashes spread uniformly over all 32 bits,
+/// and whether `Tr(a*x)` is constant across a given root depends on `a` in every one of those
+/// bits, not just the low ones a small sequential counter would vary.
+fn split_into_linear_factors(poly: &[u64]) -> Option<Vec<ShortStatementId>> {
+	let poly = poly_trim(poly.to_vec());
+	let degree = poly_degree(&poly);
+	match degree {
+		0 => Some(Vec::new()),
+		1 => Some(gf_inv(gf_mul(poly[0], gf_inv(poly[1]))) as ShortStatementId)
+			.map(|id| vec![id]),
+		_ => {
+			for attempt in 0..MAX_SPLIT_ATTEMPTS {
+				let a = split_attempt_multiplier(attempt);
+				let trace = poly_trace(&poly, a);
+				let zeros = poly_gcd(&poly, &trace);
+				let zeros_degree = poly_degree(&zeros);
+				if zeros_degree == 0 || zeros_degree == degree {
+					continue;
+				}
+				let ones = poly_gcd(&poly, &poly_add(&trace, &[1]));
+				if poly_degree(&ones) + zeros_degree != degree {
+					continue;
+				}
+				let mut roots = split_into_linear_factors(&zeros)?;
+				roots.extend(split_into_linear_factors(&ones)?);
+				return Some(roots);
+			}
+			None
+		}
+	}
+}
+
+/// Upper bound on how many scalars [`split_into_linear_factors`] tries before giving up on
+/// finding a non-trivial split; each scalar splits a polynomial of `>= 2` distinct roots roughly
+/// in half, so this is generous even for repeated bad luck.
+const MAX_SPLIT_ATTEMPTS: u32 = 256;
+
+/// Derives the `attempt`-th trial scalar for [`split_into_linear_factors`] via a fixed-output
+/// integer hash (a golden-ratio multiply followed by the first mixing round of MurmurHash3's
+/// `fmix64`), so successive attempts are spread across the full
+/// `32`-bit field instead of only varying its low bits the way a plain counter would.
+fn split_attempt_multiplier(attempt: u32) -> u64 {
+	let mut z = (attempt as u64).wrapping_add(1).wrapping_mul(0x9E3779B97F4A7C15);
+	z = (z ^ (z >> 33)).wrapping_mul(0xFF51AFD7ED558CCD);
+	z ^= z >> 33;
+	match z & 0xFFFF_FFFF {
+		0 => 1,
+		nonzero => nonzero,
+	}
+}
+
+/// Computes `Tr(a*x) mod poly = sum_(i=0..31) (a*x)^(2^i) mod poly`, the trace of `a*x` from
+/// `GF(2^32)` down to its `GF(2)` prime field, reduced modulo `poly`.
+fn poly_trace(poly: &[u64], a: u64) -> Vec<u64> {
+	let mut power = poly_trim(vec![0, a]);
+	let mut trace = power.clone();
+	for _ in 1..32 {
+		power = poly_mod(&poly_square(&power), poly);
+		trace = poly_add(&trace, &power);
+	}
+	trace
+}
+
+/// The formal derivative of `poly` (lowest-degree coefficient first). In characteristic 2 the
+/// derivative of `c * x^i` is `c * x^(i-1)` when `i` is odd and `0` when `i` is even.
+fn poly_derivative(poly: &[u64]) -> Vec<u64> {
+	let mut derivative = vec![0u64; poly.len().saturating_sub(1).max(1)];
+	for (i, coeff) in poly.iter().enumerate().skip(1).step_by(2) {
+		derivative[i - 1] = *coeff;
+	}
+	poly_trim(derivative)
+}
+
+/// Squares a polynomial over `GF(2^32)`. In characteristic 2, `(sum c_i x^i)^2 = sum c_i^2 x^(2i)`
+/// since cross terms always appear an even number of times and cancel.
+fn poly_square(poly: &[u64]) -> Vec<u64> {
+	let mut squared = vec![0u64; poly.len() * 2];
+	for (i, coeff) in poly.iter().enumerate() {
+		squared[2 * i] = gf_mul(*coeff, *coeff);
+	}
+	poly_trim(squared)
+}
+
+/// Remainder of dividing `poly` by `modulus` over `GF(2^32)`.
+fn poly_mod(poly: &[u64], modulus: &[u64]) -> Vec<u64> {
+	let modulus = poly_trim(modulus.to_vec());
+	let modulus_degree = poly_degree(&modulus);
+	let leading_inv = gf_inv(modulus[modulus_degree]);
+
+	let mut remainder = poly_trim(poly.to_vec());
+	while !poly_is_zero(&remainder) && poly_degree(&remainder) >= modulus_degree {
+		let remainder_degree = poly_degree(&remainder);
+		let scale = gf_mul(remainder[remainder_degree], leading_inv);
+		let shift = remainder_degree - modulus_degree;
+		for (i, coeff) in modulus.iter().enumerate() {
+			remainder[shift + i] = gf_add(remainder[shift + i], gf_mul(scale, *coeff));
+		}
+		remainder = poly_trim(remainder);
+	}
+	remainder
+}
+
+/// Greatest common divisor of two polynomials over `GF(2^32)`, via the Euclidean algorithm.
+fn poly_gcd(a: &[u64], b: &[u64]) -> Vec<u64> {
+	let mut a = poly_trim(a.to_vec());
+	let mut b = poly_trim(b.to_vec());
+	while !poly_is_zero(&b) {
+		let remainder = poly_mod(&a, &b);
+		a = b;
+		b = remainder;
+	}
+	a
+}
+
+/// Sum of two polynomials over `GF(2^32)` (coordinate-wise field addition).
+fn poly_add(a: &[u64], b: &[u64]) -> Vec<u64> {
+	let mut sum = vec![0u64; a.len().max(b.len())];
+	for (i, coeff) in a.iter().enumerate() {
+		sum[i] = *coeff;
+	}
+	for (i, coeff) in b.iter().enumerate() {
+		sum[i] = gf_add(sum[i], *coeff);
+	}
+	poly_trim(sum)
+}
+
+/// Drops trailing zero coefficients, keeping at least the constant term.
+fn poly_trim(mut poly: Vec<u64>) -> Vec<u64> {
+	while poly.len() > 1 && *poly.last().expect("poly is non-empty") == 0 {
+		poly.pop();
+	}
+	poly
+}
+
+/// Degree of `poly` (lowest-degree coefficient first), or `0` for the zero polynomial.
+fn poly_degree(poly: &[u64]) -> usize {
+	poly.iter().rposition(|c| *c != 0).unwrap_or(0)
+}
+
+/// Whether `poly` is the zero polynomial.
+fn poly_is_zero(poly: &[u64]) -> bool {
+	poly.iter().all(|c| *c == 0)
+}
+
+/// Reputation change applied to a peer that sends a request/response payload we cannot even
+/// decode, answers with an unexpected response, or whose reconciliation request fails.
+const MALFORMED_REQUEST: ReputationChange =
+	ReputationChange::new(-(1 << 4), "Malformed statement reconciliation request");
+
+/// Largest request or response the reconciliation protocol will send or accept.
+const MAX_RECONCILIATION_MESSAGE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// How many incoming reconciliation requests may be queued before new ones are rejected.
+const MAX_PENDING_RECONCILIATIONS: usize = 32;
+
+/// Builds the `/statement/sync/1` request/response protocol configuration, together with the
+/// receiving end of its inbound queue which must be driven by [`run_reconciliation_server`].
+pub fn reconciliation_protocol_config(
+	genesis_hash: impl AsRef<[u8]>,
+	fork_id: Option<&str>,
+) -> (
+	RequestResponseConfig,
+	async_channel::Receiver<IncomingRequest>,
+) {
+	let (tx, rx) = async_channel::bounded(MAX_PENDING_RECONCILIATIONS);
+	let config = RequestResponseConfig {
+		name: reconciliation_protocol_name(genesis_hash, fork_id),
+		fallback_names: Vec::new(),
+		max_request_size: MAX_RECONCILIATION_MESSAGE_SIZE,
+		max_response_size: MAX_RECONCILIATION_MESSAGE_SIZE,
+		request_timeout: RECONCILE_TIMEOUT,
+		inbound_queue: Some(tx),
+	};
+	(config, rx)
+}
+
+/// Indexes `statements` by [`short_id`], so that a decoded symmetric difference can be resolved
+/// against the local set without a linear scan per ID.
+fn index_by_short_id(
+	statements: Vec<(Hash, Statement)>,
+) -> HashMap<ShortStatementId, Vec<(Hash, Statement)>> {
+	let mut index: HashMap<ShortStatementId, Vec<(Hash, Statement)>> = HashMap::new();
+	for (hash, statement) in statements {
+		index
+			.entry(short_id(&hash))
+			.or_default()
+			.push((hash, statement));
+	}
+	index
+}
+
+/// Answers a single decoded [`ReconciliationRequest`] against `statement_store`.
+fn handle_request(
+	statement_store: &Arc<dyn StatementStore>,
+	request: ReconciliationRequest,
+) -> ReconciliationResponse {
+	match request {
+		ReconciliationRequest::EstimateSize => {
+			let len = statement_store.statements().map(|s| s.len()).unwrap_or(0) as u32;
+			ReconciliationResponse::Size { len }
+		}
+		ReconciliationRequest::Sketch {
+			capacity,
+			sketch: theirs,
+		} => {
+			let Ok(local) = statement_store.statements() else {
+				return ReconciliationResponse::DecodeFailed;
+			};
+			let local_ids = local.iter().map(|(hash, _)| short_id(hash));
+			let mut merged = StatementSketch::build(local_ids, capacity);
+			merged.merge(&theirs);
+			match merged.decode() {
+				Some(difference) => {
+					let index = index_by_short_id(local);
+					let (send_to_you, request_from_you) = classify_difference(&index, &difference);
+					ReconciliationResponse::Reconciled {
+						send_to_you,
+						request_from_you,
+					}
+				}
+				None => ReconciliationResponse::DecodeFailed,
+			}
+		}
+		ReconciliationRequest::Push { statements } => {
+			for statement in statements {
+				let _ = statement_store.submit(statement, StatementSource::Network);
+			}
+			ReconciliationResponse::Ack
+		}
+	}
+}
+
+/// Serves incoming `/statement/sync/1` requests against `statement_store` until the inbound queue
+/// is closed. Spawned as an independent task by [`crate::StatementHandlerPrototype::build`],
+/// mirroring how block and justification requests are served by their own standalone tasks.
+pub async fn run_reconciliation_server(
+	incoming: async_channel::Receiver<IncomingRequest>,
+	statement_store: Arc<dyn StatementStore>,
+) {
+	let mut incoming = incoming;
+	while let Some(IncomingRequest {
+		peer,
+		payload,
+		pending_response,
+	}) = incoming.next().await
+	{
+		let outgoing = match ReconciliationRequest::decode(&mut &payload[..]) {
+			Ok(request) => {
+				let response = handle_request(&statement_store, request);
+				OutgoingResponse {
+					result: Ok(response.encode()),
+					reputation_changes: Vec::new(),
+					sent_feedback: None,
+				}
+			}
+			Err(_) => OutgoingResponse {
+				result: Err(()),
+				reputation_changes: vec![MALFORMED_REQUEST],
+				sent_feedback: None,
+			},
+		};
+
+		if pending_response.send(outgoing).is_err() {
+			log::debug!(
+				target: LOG_TARGET,
+				"{peer}: failed to send statement reconciliation response",
+			);
+		}
+	}
+}
+
+/// Runs one full reconciliation round with `peer`: estimates the symmetric difference, exchanges
+/// sketches (retrying with a larger capacity on decode failure, up to [`MAX_CAPACITY`]), imports
+/// whatever the peer pushes back, and pushes whatever the peer asked for.
+///
+/// Falls back to sending our entire statement set, bounded by [`MAX_RECONCILIATION_MESSAGE_SIZE`]
+/// worth of statements, if the difference still cannot be decoded at maximum capacity.
+pub async fn reconcile_with_peer<N: NetworkRequest>(
+	network: &N,
+	protocol_name: ProtocolName,
+	peer: PeerId,
+	statement_store: &Arc<dyn StatementStore>,
+) -> Result<(), ReputationChange> {
+	let Ok(local) = statement_store.statements() else {
+		log::debug!(target: LOG_TARGET, "{peer}: failed to read local statements for reconciliation");
+		return Ok(());
+	};
+
+	let send_request = |request: ReconciliationRequest| {
+		let network = &network;
+		let protocol_name = protocol_name.clone();
+		async move {
+			let (response, _) = network
+				.request(
+					peer,
+					protocol_name,
+					request.encode(),
+					None,
+					IfDisconnected::ImmediateError,
+				)
+				.await
+				.map_err(|_| MALFORMED_REQUEST)?;
+			ReconciliationResponse::decode(&mut &response[..]).map_err(|_| MALFORMED_REQUEST)
+		}
+	};
+
+	let their_len = match send_request(ReconciliationRequest::EstimateSize).await? {
+		ReconciliationResponse::Size { len } => len,
+		_ => return Err(MALFORMED_REQUEST),
+	};
+
+	let mut capacity = capacity_for_estimate(estimate_diff(local.len() as u32, their_len));
+	loop {
+		let sketch = StatementSketch::build(local.iter().map(|(hash, _)| short_id(hash)), capacity);
+		let response = send_request(ReconciliationRequest::Sketch { capacity, sketch }).await?;
+
+		match response {
+			ReconciliationResponse::Reconciled {
+				send_to_you,
+				request_from_you,
+			} => {
+				for statement in send_to_you {
+					let _ = statement_store.submit(statement, StatementSource::Network);
+				}
+
+				if !request_from_you.is_empty() {
+					let index = index_by_short_id(local);
+					let wanted = request_from_you
+						.into_iter()
+						.filter_map(|id| index.get(&id).cloned())
+						.flatten()
+						.map(|(_, statement)| statement)
+						.collect();
+					let _ =
+						send_request(ReconciliationRequest::Push { statements: wanted }).await?;
+				}
+
+				return Ok(());
+			}
+			ReconciliationResponse::DecodeFailed if should_fall_back_to_flood(capacity) => {
+				log::debug!(
+					target: LOG_TARGET,
+					"{peer}: statement reconciliation sketch exceeded max capacity, falling back to full flood",
+				);
+				// Leave room for the request's variant tag and the vector's length prefix.
+				let budget = MAX_RECONCILIATION_MESSAGE_SIZE - 16;
+				let mut size = 0u64;
+				let statements = local
+					.into_iter()
+					.map(|(_, statement)| statement)
+					.take_while(|statement| {
+						size += statement.encoded_size() as u64;
+						size <= budget
+					})
+					.collect();
+				send_request(ReconciliationRequest::Push { statements }).await?;
+				return Err(on_decode_failure(&peer));
+			}
+			ReconciliationResponse::DecodeFailed => {
+				capacity = (capacity * 2).min(MAX_CAPACITY);
+				continue;
+			}
+			_ => return Err(MALFORMED_REQUEST),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ids(values: &[u32]) -> Vec<ShortStatementId> {
+		values.to_vec()
+	}
+
+	#[test]
+	fn sketch_of_identical_sets_cancels_out() {
+		let a = StatementSketch::build(ids(&[1, 2, 3, 4]), 8);
+		let mut b = StatementSketch::build(ids(&[1, 2, 3, 4]), 8);
+		b.merge(&a);
+		assert_eq!(b.decode(), Some(Vec::new()));
+	}
+
+	#[test]
+	fn sketch_recovers_symmetric_difference() {
+		let a = StatementSketch::build(ids(&[1, 2, 3, 4, 100]), 8);
+		let mut b = StatementSketch::build(ids(&[2, 3, 4, 5, 6]), 8);
+		b.merge(&a);
+		let mut difference = b.decode().expect("difference within capacity");
+		difference.sort_unstable();
+		assert_eq!(difference, vec![1, 5, 6, 100]);
+	}
+
+	#[test]
+	fn sketch_decode_fails_when_difference_exceeds_capacity() {
+		// Short IDs are truncated hashes, so realistic differences look like this rather than a
+		// tight run of small integers: with only a handful of small values, an unrelated small set
+		// can coincidentally produce the exact same sketch at a too-small capacity (a known,
+		// tolerated property of capacity-exceeded sketches, see `StatementSketch::decode`), which would
+		// make this test flaky about what it actually exercises.
+		let a = StatementSketch::build(
+			ids(&[2_746_317_214, 478_163_328, 107_420_370, 3_184_935_164, 1_181_241_944, 1_051_802_513]),
+			2,
+		);
+		let mut b = StatementSketch::build(ids(&[]), 2);
+		b.merge(&a);
+		assert_eq!(b.decode(), None);
+	}
+
+	#[test]
+	fn classify_difference_requests_unknown_ids() {
+		let local = HashMap::new();
+		let (send_to_you, request_from_you) = classify_difference(&local, &[1, 2]);
+		assert!(send_to_you.is_empty());
+		assert_eq!(request_from_you, vec![1, 2]);
+	}
+}
@@ -0,0 +1,242 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! On-demand statement fetching over `/statement/request/1`.
+//!
+//! `do_propagate_statements` never sends to [`ObservedRole::Light`](sc_network_common::role::ObservedRole::Light)
+//! peers, and any node that happened to be major-syncing during a propagation window simply
+//! misses whatever was gossiped then; neither has another way to obtain the statements it missed.
+//! This module adds a client-facing request/response protocol, answered straight from the local
+//! [`StatementStore`], and a [`StatementRequester`] handle so RPC, light-client logic, or
+//! [`StatementHandler`](crate::StatementHandler) itself can explicitly pull statements from a
+//! specific peer rather than depend purely on push gossip.
+
+use crate::{Statements, LOG_TARGET};
+use codec::{Decode, Encode};
+use futures::prelude::*;
+use sc_network::{
+	config::RequestResponseConfig,
+	request_responses::{IncomingRequest, OutgoingResponse},
+	types::ProtocolName,
+	IfDisconnected, NetworkRequest,
+};
+use sc_network_types::PeerId;
+use sp_statement_store::{Hash, StatementStore, Topic};
+use std::{collections::HashSet, sync::Arc};
+
+/// Largest request or response the statement request protocol will send or accept.
+const MAX_REQUEST_MESSAGE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// How many incoming statement requests may be queued before new ones are rejected.
+const MAX_PENDING_REQUESTS: usize = 32;
+
+/// How long an outgoing statement request may take before it is considered failed.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Reputation change for a peer that sends a request payload we cannot even decode.
+const MALFORMED_REQUEST: sc_network::ReputationChange =
+	sc_network::ReputationChange::new(-(1 << 4), "Malformed statement request");
+
+/// Returns the protocol name for the on-demand statement request/response protocol.
+pub fn request_protocol_name<Hash: AsRef<[u8]>>(
+	genesis_hash: Hash,
+	fork_id: Option<&str>,
+) -> ProtocolName {
+	let genesis_hash = genesis_hash.as_ref();
+	if let Some(fork_id) = fork_id {
+		format!("/{}/{}/statement/request/1", array_bytes::bytes2hex("", genesis_hash), fork_id)
+	} else {
+		format!("/{}/statement/request/1", array_bytes::bytes2hex("", genesis_hash))
+	}
+	.into()
+}
+
+/// What to fetch from a peer's [`StatementStore`].
+#[derive(Debug, Encode, Decode)]
+pub enum StatementRequest {
+	/// A specific set of statements, identified by their hash.
+	Hashes(Vec<Hash>),
+	/// All statements matching every one of the given topics (e.g. a channel/account inbox).
+	Topics(Vec<Topic>),
+	/// Everything the responder holds except the hashes the requester already reports knowing;
+	/// the closest this store's API allows to "everything since digest X" without it exposing a
+	/// sequence cursor of its own.
+	Since {
+		/// Hashes the requester already has and does not need resent.
+		already_known: Vec<Hash>,
+	},
+}
+
+/// Response to a [`StatementRequest`].
+#[derive(Debug, Encode, Decode)]
+pub struct StatementResponse {
+	/// The statements the responder could find for the request.
+	pub statements: Statements,
+}
+
+/// Builds the `/statement/request/1` request/response protocol configuration, together with the
+/// receiving end of its inbound queue which must be driven by [`run_request_server`].
+pub fn request_protocol_config(
+	genesis_hash: impl AsRef<[u8]>,
+	fork_id: Option<&str>,
+) -> (RequestResponseConfig, async_channel::Receiver<IncomingRequest>) {
+	let (tx, rx) = async_channel::bounded(MAX_PENDING_REQUESTS);
+	let config = RequestResponseConfig {
+		name: request_protocol_name(genesis_hash, fork_id),
+		fallback_names: Vec::new(),
+		max_request_size: MAX_REQUEST_MESSAGE_SIZE,
+		max_response_size: MAX_REQUEST_MESSAGE_SIZE,
+		request_timeout: REQUEST_TIMEOUT,
+		inbound_queue: Some(tx),
+	};
+	(config, rx)
+}
+
+fn answer_request(
+	statement_store: &Arc<dyn StatementStore>,
+	request: StatementRequest,
+) -> StatementResponse {
+	let statements = match request {
+		StatementRequest::Hashes(hashes) => hashes
+			.iter()
+			.filter_map(|hash| statement_store.statement(hash).ok().flatten())
+			.collect(),
+		StatementRequest::Topics(topics) => statement_store
+			.statements()
+			.map(|statements| {
+				statements
+					.into_iter()
+					.map(|(_, statement)| statement)
+					.filter(|statement| {
+						topics.iter().all(|topic| {
+							(0..).map_while(|i| statement.topic(i)).any(|t| t == *topic)
+						})
+					})
+					.collect()
+			})
+			.unwrap_or_default(),
+		StatementRequest::Since { already_known } => {
+			let already_known: HashSet<Hash> = already_known.into_iter().collect();
+			statement_store
+				.statements()
+				.map(|statements| {
+					statements
+						.into_iter()
+						.filter(|(hash, _)| !already_known.contains(hash))
+						.map(|(_, statement)| statement)
+						.collect()
+				})
+				.unwrap_or_default()
+		},
+	};
+	StatementResponse { statements }
+}
+
+/// Serves incoming `/statement/request/1` requests against `statement_store` until the inbound
+/// queue is closed. Spawned as an independent task by
+/// [`StatementHandlerPrototype::build`](crate::StatementHandlerPrototype::build).
+pub async fn run_request_server(
+	incoming: async_channel::Receiver<IncomingRequest>,
+	statement_store: Arc<dyn StatementStore>,
+) {
+	let mut incoming = incoming;
+	while let Some(IncomingRequest { peer, payload, pending_response }) = incoming.next().await {
+		let outgoing = match StatementRequest::decode(&mut &payload[..]) {
+			Ok(request) => {
+				let response = answer_request(&statement_store, request);
+				OutgoingResponse {
+					result: Ok(response.encode()),
+					reputation_changes: Vec::new(),
+					sent_feedback: None,
+				}
+			},
+			Err(_) => OutgoingResponse {
+				result: Err(()),
+				reputation_changes: vec![MALFORMED_REQUEST],
+				sent_feedback: None,
+			},
+		};
+
+		if pending_response.send(outgoing).is_err() {
+			log::debug!(target: LOG_TARGET, "{peer}: failed to send statement request response");
+		}
+	}
+}
+
+/// Handle for pulling statements from a specific peer on demand, instead of waiting for push
+/// gossip to deliver them.
+pub struct StatementRequester<N> {
+	network: N,
+	protocol_name: ProtocolName,
+}
+
+impl<N> Clone for StatementRequester<N>
+where
+	N: Clone,
+{
+	fn clone(&self) -> Self {
+		Self { network: self.network.clone(), protocol_name: self.protocol_name.clone() }
+	}
+}
+
+impl<N: NetworkRequest> StatementRequester<N> {
+	/// Creates a new requester issuing `/statement/request/1` requests over `network`.
+	pub fn new(network: N, protocol_name: ProtocolName) -> Self {
+		Self { network, protocol_name }
+	}
+
+	async fn request(&self, peer: PeerId, request: StatementRequest) -> Result<Statements, ()> {
+		let (response, _) = self
+			.network
+			.request(
+				peer,
+				self.protocol_name.clone(),
+				request.encode(),
+				None,
+				IfDisconnected::ImmediateError,
+			)
+			.await
+			.map_err(|_| ())?;
+		StatementResponse::decode(&mut &response[..]).map(|r| r.statements).map_err(|_| ())
+	}
+
+	/// Requests specific statements, by hash, from `peer`.
+	pub async fn request_by_hashes(&self, peer: PeerId, hashes: Vec<Hash>) -> Result<Statements, ()> {
+		self.request(peer, StatementRequest::Hashes(hashes)).await
+	}
+
+	/// Requests all statements matching every one of `topics` (e.g. an account/channel inbox)
+	/// from `peer`.
+	pub async fn request_by_topics(
+		&self,
+		peer: PeerId,
+		topics: Vec<Topic>,
+	) -> Result<Statements, ()> {
+		self.request(peer, StatementRequest::Topics(topics)).await
+	}
+
+	/// Requests everything `peer` holds that is not in `already_known`, used to backfill gaps
+	/// left by missed gossip (e.g. right after major syncing finishes).
+	pub async fn request_since(
+		&self,
+		peer: PeerId,
+		already_known: Vec<Hash>,
+	) -> Result<Statements, ()> {
+		self.request(peer, StatementRequest::Since { already_known }).await
+	}
+}